@@ -1,10 +1,10 @@
 use std::{
-    collections::{HashMap, BTreeSet},
+    collections::{BTreeMap, HashMap, BTreeSet},
     fmt, mem,
     ops::Range,
 };
 
-use abi::size::Size;
+use abi::{align::Align, size::Size};
 use index_vec::{define_index_type, IndexVec};
 use mir::{
     syntax::{TyId, TyKind},
@@ -38,6 +38,24 @@ impl AbstractByte {
     }
 }
 
+/// The initialization status of a byte range, as reported by [`Run::get_init`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitStatus {
+    AllInit,
+    AllUninit,
+    Mixed,
+}
+
+/// Whether an allocation's contents can still be written to, mirroring Miri's
+/// `Allocation::mutability`. `static`s and const-promoteds are frozen to `Immutable` once their
+/// initializer finishes running, so the generator can hand out `&T` to them knowing no later
+/// write can observe a different value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutability {
+    Mutable,
+    Immutable,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BorrowType {
     Raw,
@@ -54,19 +72,114 @@ pub struct Borrow {
 /// A Run represents a contiguous region of memory free of padding
 #[derive(Debug, Clone)]
 pub struct Run {
-    bytes: Box<[AbstractByte]>,
+    size: Size,
+    /// Initialization status, stored as a run-length-coalesced map rather than one
+    /// `AbstractByte` per byte, so a freshly allocated (or fully filled) run costs O(1)
+    /// regardless of size instead of O(size).
+    init: RangeMap<AbstractByte>,
     ref_stack: RangeMap<Vec<Borrow>>,
+    /// Provenance, keyed by the starting byte offset of the `PTR_SIZE`-wide pointer value it
+    /// was written at. Mirrors Miri's relocation table: a byte range that is `Init` but has no
+    /// entry here starting exactly at its offset holds a plain integer, not a pointer.
+    relocations: BTreeMap<u64, AllocId>,
+    /// This run's own alignment. Alignment is tracked per run, not per allocation, so a run
+    /// backing an under-aligned field (e.g. inside a `#[repr(packed)]` struct) can be less
+    /// aligned than the other runs of its allocation.
+    align: Align,
 }
 
 impl Run {
-    pub fn new_uninit(size: Size) -> Self {
-        let bytes = vec![AbstractByte::Uninit; size.bytes() as usize].into_boxed_slice();
+    pub fn new_uninit(size: Size, align: Align) -> Self {
+        let init = RangeMap::new(size, AbstractByte::Uninit);
         let ref_stack = RangeMap::new(size, vec![]);
-        Self { bytes, ref_stack }
+        Self {
+            size,
+            init,
+            ref_stack,
+            relocations: BTreeMap::new(),
+            align,
+        }
     }
 
     pub fn size(&self) -> Size {
-        Size::from_bytes(self.bytes.len())
+        self.size
+    }
+
+    pub fn align(&self) -> Align {
+        self.align
+    }
+
+    /// Reports whether `[offset, offset + len)` is uniformly initialized, uniformly
+    /// uninitialized, or a mix of both.
+    pub fn get_init(&self, offset: Size, len: Size) -> InitStatus {
+        let mut any_init = false;
+        let mut any_uninit = false;
+        for (_, byte) in self.init.iter(offset, len) {
+            match byte {
+                AbstractByte::Init => any_init = true,
+                AbstractByte::Uninit => any_uninit = true,
+            }
+            if any_init && any_uninit {
+                return InitStatus::Mixed;
+            }
+        }
+        if any_uninit {
+            InitStatus::AllUninit
+        } else {
+            InitStatus::AllInit
+        }
+    }
+
+    /// Fast uniform-range check: true iff every byte in `[offset, offset + len)` is `Init`.
+    pub fn is_init(&self, offset: Size, len: Size) -> bool {
+        self.get_init(offset, len) == InitStatus::AllInit
+    }
+
+    /// Marks `[offset, offset + len)` as `Init` or `Uninit`. The underlying range map
+    /// coalesces adjacent equal entries, so filling a whole run collapses to a single entry
+    /// instead of one per byte.
+    pub fn set_init(&mut self, offset: Size, len: Size, init: bool) {
+        let val = if init {
+            AbstractByte::Init
+        } else {
+            AbstractByte::Uninit
+        };
+        for (_, byte) in self.init.iter_mut(offset, len) {
+            *byte = val;
+        }
+    }
+
+    fn relocation_at(&self, offset: Size) -> Option<AllocId> {
+        self.relocations.get(&offset.bytes()).copied()
+    }
+
+    fn set_relocation(&mut self, offset: Size, alloc_id: AllocId) {
+        self.relocations.insert(offset.bytes(), alloc_id);
+    }
+
+    /// Clears every relocation whose `PTR_SIZE`-wide range intersects `[offset, offset + len)`,
+    /// whether or not it is fully contained within it. A write that only partially overlaps a
+    /// pointer's bytes destroys that pointer's provenance.
+    fn clear_relocations(&mut self, offset: Size, len: Size) {
+        let start = offset.bytes();
+        let end = start + len.bytes();
+        let ptr_size = BasicMemory::PTR_SIZE.bytes();
+        self.relocations
+            .retain(|&reloc_start, _| reloc_start + ptr_size <= start || reloc_start >= end);
+    }
+
+    /// Relocations whose full `PTR_SIZE` range lies within `[offset, offset + len)`, returned
+    /// as `(offset relative to the start of the range, target AllocId)` pairs. A relocation
+    /// that only partially overlaps the range is provenance that a byte-wise copy would corrupt,
+    /// so it is silently dropped here rather than returned.
+    fn relocations_in(&self, offset: Size, len: Size) -> impl Iterator<Item = (Size, AllocId)> + '_ {
+        let start = offset.bytes();
+        let end = start + len.bytes();
+        let ptr_size = BasicMemory::PTR_SIZE.bytes();
+        self.relocations
+            .range(start..end)
+            .filter(move |(&reloc_start, _)| reloc_start + ptr_size <= end)
+            .map(move |(&reloc_start, &alloc_id)| (Size::from_bytes(reloc_start - start), alloc_id))
     }
 
     pub fn add_borrow(
@@ -186,10 +299,10 @@ impl RunAndOffset {
 struct Allocation {
     /// The data stored in this allocation.
     runs: IndexVec<RunId, Run>,
-    /// The alignment that was requested for this allocation.
-    // align: Align,
     /// Whether this allocation is still live.
     live: bool,
+    /// Whether this allocation can still be written to.
+    mutability: Mutability,
 }
 
 impl Allocation {
@@ -207,11 +320,12 @@ impl Allocation {
 pub struct AllocationBuilder {
     alloc_id: AllocId,
     runs: IndexVec<RunId, Run>,
+    mutability: Mutability,
 }
 
 impl AllocationBuilder {
-    pub fn new_run(&mut self, size: Size) -> RunAndOffset {
-        let run = Run::new_uninit(size);
+    pub fn new_run(&mut self, size: Size, align: Align) -> RunAndOffset {
+        let run = Run::new_uninit(size, align);
         let run_id = self.runs.push(run);
         RunAndOffset(run_id, Size::ZERO)
     }
@@ -220,10 +334,18 @@ impl AllocationBuilder {
         self.alloc_id
     }
 
+    /// Marks the allocation being built as immutable, e.g. for a `static` or const-promoted
+    /// whose initializer is complete. Prefer [`BasicMemory::freeze`] for one whose initializer
+    /// runs after the allocation already exists.
+    pub fn mark_immutable(&mut self) {
+        self.mutability = Mutability::Immutable;
+    }
+
     fn build(self) -> Allocation {
         Allocation {
             runs: self.runs,
             live: true,
+            mutability: self.mutability,
         }
     }
 }
@@ -302,6 +424,7 @@ pub struct BasicMemory {
 
 impl BasicMemory {
     const PTR_SIZE: Size = Size::from_bytes_const(mem::size_of::<*const ()>() as u64);
+    const PTR_ALIGN: Align = Align::from_bytes_const(mem::align_of::<*const ()>() as u64);
 
     pub fn new() -> Self {
         Self {
@@ -318,6 +441,7 @@ impl BasicMemory {
         let mut builder = AllocationBuilder {
             alloc_id,
             runs: IndexVec::new(),
+            mutability: Mutability::Mutable,
         };
         build(&mut builder);
         self.allocations.push(builder.build())
@@ -331,32 +455,208 @@ impl BasicMemory {
         self.allocations[alloc_id].live
     }
 
-    pub fn bytes(&self, run_ptr: RunPointer) -> &[AbstractByte] {
+    /// Flips an allocation from mutable to immutable, e.g. once a `static`'s initializer has
+    /// finished running. Irreversible: there is no corresponding `unfreeze`.
+    pub fn freeze(&mut self, alloc_id: AllocId) {
+        self.allocations[alloc_id].mutability = Mutability::Immutable;
+    }
+
+    /// Materializes the abstract bytes in `run_ptr`'s range. Only the initialization status of
+    /// each byte is meaningful; use [`BasicMemory::get_init`]/[`BasicMemory::is_init`] instead
+    /// when only a uniform-range question is being asked, since those stay compact instead of
+    /// allocating one entry per byte.
+    pub fn bytes(&self, run_ptr: RunPointer) -> Vec<AbstractByte> {
         assert!(
             self.allocations[run_ptr.alloc_id].live,
             "can't access dead bytes"
         );
-        &self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0].bytes
-            [run_ptr.bytes_range()]
+        let run = &self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0];
+        let query_start = run_ptr.run_and_offset.1.bytes_usize();
+        let query_end = query_start + run_ptr.size.bytes_usize();
+        let mut bytes = Vec::with_capacity(run_ptr.size.bytes_usize());
+        for (range, byte) in run.init.iter(run_ptr.run_and_offset.1, run_ptr.size) {
+            // Clamp explicitly rather than trust the map to have already clipped the range to
+            // the query window, so a wider-than-requested entry can't make us overrun `size`.
+            let start = range.start.max(query_start);
+            let end = range.end.min(query_end);
+            if end > start {
+                bytes.extend(std::iter::repeat(*byte).take(end - start));
+            }
+        }
+        bytes
     }
 
-    pub fn fill(&mut self, run_ptr: RunPointer, val: AbstractByte) {
-        self.bytes_mut(run_ptr).fill(val);
+    pub fn get_init(&self, run_ptr: RunPointer) -> InitStatus {
+        self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0]
+            .get_init(run_ptr.run_and_offset.1, run_ptr.size)
     }
 
-    pub fn bytes_mut(&mut self, run_ptr: RunPointer) -> &mut [AbstractByte] {
+    pub fn is_init(&self, run_ptr: RunPointer) -> bool {
+        self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0]
+            .is_init(run_ptr.run_and_offset.1, run_ptr.size)
+    }
+
+    /// Writes `vals`, one `AbstractByte` per byte, into `run_ptr`'s range, clearing any
+    /// relocation the write overlaps. Unlike [`BasicMemory::fill`], which can only stamp a
+    /// single uniform value across the whole range, this accepts a non-uniform pattern (e.g. a
+    /// struct written field-by-field with uninitialized padding between them), so it's the way
+    /// to produce an `InitStatus::Mixed` run directly instead of only via `copy` from one.
+    pub fn bytes_mut(&mut self, run_ptr: RunPointer, vals: &[AbstractByte]) {
+        assert_eq!(
+            vals.len(),
+            run_ptr.size.bytes_usize(),
+            "vals must cover exactly run_ptr's range"
+        );
         assert!(
             self.allocations[run_ptr.alloc_id].live,
             "can't access dead bytes"
         );
-        &mut self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0].bytes
-            [run_ptr.bytes_range()]
+        assert_eq!(
+            self.allocations[run_ptr.alloc_id].mutability,
+            Mutability::Mutable,
+            "can't write to an immutable allocation"
+        );
+        let run = &mut self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0];
+        run.clear_relocations(run_ptr.run_and_offset.1, run_ptr.size);
+
+        // Write back run-length-encoded instead of byte-by-byte, so a uniform sub-range still
+        // collapses to a single entry in the underlying range map.
+        let start = run_ptr.run_and_offset.1.bytes();
+        let mut i = 0;
+        while i < vals.len() {
+            let val = vals[i];
+            let mut j = i + 1;
+            while j < vals.len() && vals[j] == val {
+                j += 1;
+            }
+            run.set_init(
+                Size::from_bytes(start + i as u64),
+                Size::from_bytes((j - i) as u64),
+                val.is_init(),
+            );
+            i = j;
+        }
+    }
+
+    pub fn fill(&mut self, run_ptr: RunPointer, val: AbstractByte) {
+        assert!(
+            self.allocations[run_ptr.alloc_id].live,
+            "can't access dead bytes"
+        );
+        assert_eq!(
+            self.allocations[run_ptr.alloc_id].mutability,
+            Mutability::Mutable,
+            "can't write to an immutable allocation"
+        );
+        let run = &mut self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0];
+        // Any write can clobber a pointer value it only partially overlaps, so provenance
+        // covering the written range is conservatively dropped here. A pointer store re-adds
+        // its relocation afterwards via `write_provenance`.
+        run.clear_relocations(run_ptr.run_and_offset.1, run_ptr.size);
+        run.set_init(run_ptr.run_and_offset.1, run_ptr.size, val.is_init());
+    }
+
+    /// Reads the provenance of a pointer-sized value, if it has any. Returns `None` both when
+    /// the bytes aren't fully initialized and when they are initialized but hold a plain integer
+    /// (no relocation covers the whole range) -- in the latter case the bytes are still readable,
+    /// just not dereferenceable.
+    pub fn read_provenance(&self, run_ptr: RunPointer) -> Option<AllocId> {
+        if run_ptr.size != Self::PTR_SIZE || !self.is_init(run_ptr) {
+            return None;
+        }
+        self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0]
+            .relocation_at(run_ptr.run_and_offset.1)
+    }
+
+    /// Records that the `PTR_SIZE` bytes at `run_ptr` encode a pointer into `alloc_id`, marking
+    /// them `Init` in the process.
+    pub fn write_provenance(&mut self, run_ptr: RunPointer, alloc_id: AllocId) {
+        assert_eq!(
+            run_ptr.size,
+            Self::PTR_SIZE,
+            "provenance can only be written to a pointer-sized range"
+        );
+        self.fill(run_ptr, AbstractByte::Init);
+        self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0]
+            .set_relocation(run_ptr.run_and_offset.1, alloc_id);
     }
 
     pub fn copy(&mut self, dst: RunPointer, src: RunPointer) {
         assert_eq!(dst.size, src.size);
-        let tmp = self.bytes(src).to_vec();
-        self.bytes_mut(dst).copy_from_slice(&tmp)
+        assert!(
+            self.allocations[dst.alloc_id].live,
+            "can't access dead bytes"
+        );
+        assert_eq!(
+            self.allocations[dst.alloc_id].mutability,
+            Mutability::Mutable,
+            "can't write to an immutable allocation"
+        );
+        let src_bytes = self.bytes(src);
+        let relocations: SmallVec<[(Size, AllocId); 4]> = self.allocations[src.alloc_id].runs
+            [src.run_and_offset.0]
+            .relocations_in(src.run_and_offset.1, src.size)
+            .collect();
+
+        let dst_run = &mut self.allocations[dst.alloc_id].runs[dst.run_and_offset.0];
+        dst_run.clear_relocations(dst.run_and_offset.1, dst.size);
+
+        // Write back run-length-encoded instead of byte-by-byte, so copying a uniformly
+        // initialized (or uninitialized) range stays compact in the destination.
+        let dst_start = dst.run_and_offset.1.bytes();
+        let mut i = 0;
+        while i < src_bytes.len() {
+            let val = src_bytes[i];
+            let mut j = i + 1;
+            while j < src_bytes.len() && src_bytes[j] == val {
+                j += 1;
+            }
+            dst_run.set_init(
+                Size::from_bytes(dst_start + i as u64),
+                Size::from_bytes((j - i) as u64),
+                val.is_init(),
+            );
+            i = j;
+        }
+
+        // Only relocations that survived intact (fully within the copied range) carry over;
+        // ones the run-length pass above already overwrote with plain init/uninit bytes stay
+        // cleared, matching how a partial pointer overwrite destroys its provenance elsewhere.
+        for (rel_offset, alloc_id) in relocations {
+            dst_run.set_relocation(Size::from_bytes(dst_start + rel_offset.bytes()), alloc_id);
+        }
+    }
+
+    /// Writes `count` back-to-back copies of `src` into `dst`, replicating both init state and
+    /// relocations for each repeat. `dst` must be exactly `count` times `src`'s size. This is
+    /// the primitive the generator uses to build `[expr; N]` array literals and slice fills
+    /// without emitting `count` individual copies itself.
+    pub fn copy_repeat(&mut self, dst: RunPointer, src: RunPointer, count: usize) {
+        assert_eq!(
+            dst.size.bytes_usize(),
+            src.size.bytes_usize() * count,
+            "copy_repeat destination must be exactly `count` copies of the source"
+        );
+        for i in 0..count {
+            let offset =
+                Size::from_bytes(dst.run_and_offset.1.bytes() + i as u64 * src.size.bytes());
+            let dst_i = RunPointer {
+                alloc_id: dst.alloc_id,
+                run_and_offset: RunAndOffset(dst.run_and_offset.0, offset),
+                size: src.size,
+            };
+            self.copy(dst_i, src);
+        }
+    }
+
+    /// Whether `run_ptr` is aligned to `required`, i.e. an access of that alignment through
+    /// this pointer is well-defined. This holds when the run was created with at least
+    /// `required`'s alignment and the run offset is itself a multiple of `required`
+    /// (an under-aligned run, e.g. a `#[repr(packed)]` field, fails the first check;
+    /// a well-aligned run accessed at a misaligned offset fails the second).
+    pub fn is_aligned(&self, run_ptr: RunPointer, required: Align) -> bool {
+        let run = &self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0];
+        run.align() >= required && run_ptr.run_and_offset.1.bytes() % required.bytes() == 0
     }
 
     /// Returns Size for types with guaranteed size.
@@ -387,6 +687,32 @@ impl BasicMemory {
         })
     }
 
+    /// Returns the default (non-packed) alignment for types with a guaranteed size, mirroring
+    /// [`BasicMemory::ty_size`]. A run's actual alignment may be relaxed below this, e.g. for
+    /// a `#[repr(packed)]` field, which the generator models by requesting `Align::ONE` from
+    /// `AllocationBuilder::new_run` instead of this value.
+    pub fn ty_align(ty: TyId, tcx: &TyCtxt) -> Option<Align> {
+        Some(match ty {
+            TyCtxt::UNIT => Align::ONE,
+            TyCtxt::BOOL => Align::from_bytes(1),
+            TyCtxt::CHAR => Align::from_bytes(4),
+            TyCtxt::I8 | TyCtxt::U8 => Align::from_bits(8),
+            TyCtxt::I16 | TyCtxt::U16 => Align::from_bits(16),
+            TyCtxt::I32 | TyCtxt::U32 => Align::from_bits(32),
+            TyCtxt::I64 | TyCtxt::U64 => Align::from_bits(64),
+            TyCtxt::I128 | TyCtxt::U128 => Align::from_bits(128),
+            TyCtxt::F32 => Align::from_bits(32),
+            TyCtxt::F64 => Align::from_bits(64),
+            TyCtxt::ISIZE | TyCtxt::USIZE => Self::PTR_ALIGN,
+            _ => match ty.kind(tcx) {
+                TyKind::RawPtr(..) => Self::PTR_ALIGN,
+                TyKind::Ref(..) => Self::PTR_ALIGN,
+                TyKind::Array(ty, _) => return Self::ty_align(*ty, tcx),
+                _ => return None,
+            },
+        })
+    }
+
     pub fn copy_ref(
         &mut self,
         new: ProjectionIndex,
@@ -516,6 +842,9 @@ impl BasicMemory {
     }
 
     pub fn can_write_through(&self, run_ptr: RunPointer, edge: ProjectionIndex) -> bool {
+        if self.allocations[run_ptr.alloc_id].mutability == Mutability::Immutable {
+            return false;
+        }
         self.allocations[run_ptr.alloc_id].runs[run_ptr.run_and_offset.0].can_write_through(
             run_ptr.run_and_offset.1,
             run_ptr.size,